@@ -3,8 +3,8 @@ use std::{
     io::BufReader,
     path::Path,
     sync::{
-        atomic::{AtomicU32, Ordering},
-        OnceLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, OnceLock,
     },
     time::Duration,
 };
@@ -20,6 +20,7 @@ use actix_web_httpauth::extractors::bearer::BearerAuth;
 use anyhow::Result;
 use rand::Rng;
 use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
     header::{self, HeaderValue},
     StatusCode,
 };
@@ -34,7 +35,9 @@ const TIMEOUT: u16 = 360;
 // This struct represents state
 struct AppState {
     api_key: Option<String>,
-    dl_session: String,
+    session_pool: Arc<SessionPool>,
+    session_cooldown: Duration,
+    session_max_attempts: usize,
 }
 
 pub struct Serve(pub BootArgs);
@@ -52,11 +55,38 @@ impl Serve {
             .init();
 
         // Init client pool
-        let client = Client::new(self.0.proxies.clone())?;
+        let dns_resolver = match (&self.0.dns_nameserver, &self.0.doh_endpoint) {
+            (_, Some(endpoint)) => Some(DnsResolverConfig::DnsOverHttps(endpoint.clone())),
+            (Some(nameserver), None) => Some(DnsResolverConfig::Nameserver(nameserver.parse()?)),
+            (None, None) => None,
+        };
+        let proxy_count = self.0.proxies.as_ref().map(|p| p.len()).unwrap_or(1);
+        let client_identities = Self::load_client_identities(
+            &self.0.client_cert,
+            &self.0.client_key,
+            proxy_count,
+        )?;
+        let client = Client::new(self.0.proxies.clone(), dns_resolver, client_identities)?;
         let _ = CLIENT.set(client);
+        actix_web::rt::spawn(proxy_health_check_loop());
+
+        // Init translation cache
+        let cache = Cache::new(
+            Duration::from_secs(self.0.cache_ttl),
+            self.0.cache_capacity,
+        );
+        let _ = CACHE.set(cache);
+
+        if self.0.dl_session.is_empty() {
+            return Err(anyhow::anyhow!(
+                "at least one dl_session token must be configured"
+            ));
+        }
 
         let api_key = self.0.api_key.clone();
-        let dl_session = self.0.dl_session.clone();
+        let session_pool = Arc::new(SessionPool::new(self.0.dl_session.clone()));
+        let session_cooldown = Duration::from_secs(self.0.dl_session_cooldown);
+        let session_max_attempts = session_pool.len().max(1);
 
         api_key.as_ref().map(|_| {
             tracing::info!("API key is required");
@@ -78,7 +108,9 @@ impl Serve {
                 .wrap(Logger::default())
                 .app_data(web::Data::new(AppState {
                     api_key: api_key.clone(),
-                    dl_session: dl_session.clone(),
+                    session_pool: session_pool.clone(),
+                    session_cooldown,
+                    session_max_attempts,
                 }))
                 .route("/", web::get().to(manual_hello))
                 .route("/translate", web::post().to(translate))
@@ -125,6 +157,56 @@ impl Serve {
 
         Ok(tls_config)
     }
+
+    /// Load client (mTLS) identities used to authenticate outbound connections, e.g. when
+    /// an egress proxy or the upstream itself requires a client certificate. When a single
+    /// `client_cert`/`client_key` pair is configured it's shared by every client in the
+    /// pool; when one pair per proxy is configured, each proxy gets its own identity.
+    fn load_client_identities(
+        client_cert: &Option<Vec<String>>,
+        client_key: &Option<Vec<String>>,
+        pool_size: usize,
+    ) -> Result<Vec<Option<reqwest::Identity>>> {
+        let (certs, keys) = match (client_cert, client_key) {
+            (Some(certs), Some(keys)) => (certs, keys),
+            (None, None) => return Ok(vec![None; pool_size]),
+            (Some(_), None) => {
+                return Err(anyhow::anyhow!(
+                    "client_cert is configured but client_key is not — both are required for mTLS"
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "client_key is configured but client_cert is not — both are required for mTLS"
+                ))
+            }
+        };
+
+        if certs.len() == 1 && keys.len() == 1 {
+            let identity = Self::read_identity(&certs[0], &keys[0])?;
+            return Ok(vec![Some(identity); pool_size]);
+        }
+
+        if certs.len() != keys.len() {
+            return Err(anyhow::anyhow!(
+                "client_cert has {} entries but client_key has {} — configure one pair per proxy or a single shared pair",
+                certs.len(),
+                keys.len()
+            ));
+        }
+
+        certs
+            .iter()
+            .zip(keys.iter())
+            .map(|(cert, key)| Self::read_identity(cert, key).map(Some))
+            .collect()
+    }
+
+    fn read_identity<P: AsRef<Path>>(cert: P, key: P) -> Result<reqwest::Identity> {
+        let mut pem = std::fs::read(cert)?;
+        pem.extend_from_slice(&std::fs::read(key)?);
+        reqwest::Identity::from_pem(&pem).map_err(Into::into)
+    }
 }
 
 async fn manual_hello() -> impl Responder {
@@ -141,6 +223,26 @@ async fn translate(
     let id = get_random_number() + 1;
     let number_alternative = 0.clamp(0, 3);
 
+    let source_lang = req.source_lang.to_uppercase();
+    let target_lang = req.target_lang.to_uppercase();
+    let cache_key = Cache::key(&req.text, &source_lang, &target_lang);
+
+    if let Some(cached) =
+        get_cache().and_then(|cache| cache.get(cache_key, &req.text, &source_lang, &target_lang))
+    {
+        let response = json!({
+            "code": StatusCode::OK.as_u16(),
+            "id": id,
+            "data": cached.data,
+            "alternatives": cached.alternatives,
+            "source_lang": req.source_lang,
+            "target_lang": req.target_lang,
+            "method": "Free",
+        });
+
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
     let post_data = json!({
         "jsonrpc": "2.0",
         "method": "LMT_handle_texts",
@@ -152,8 +254,8 @@ async fn translate(
             }],
             "splitting": "newlines",
             "lang": {
-                "source_lang_user_selected": req.source_lang.to_uppercase(),
-                "target_lang": req.target_lang.to_uppercase(),
+                "source_lang_user_selected": &source_lang,
+                "target_lang": &target_lang,
             },
             "timestamp": get_timestamp(get_i_count(&req.text))?,
             "commonJobParams": {
@@ -171,21 +273,55 @@ async fn translate(
         body = body.replace("\"method\":\"", "\"method\": \"");
     }
 
-    let resp = get_client()?
-        .post("https://api.deepl.com/jsonrpc")
-        .header(header::CONTENT_TYPE, "application/json")
-        .header(header::COOKIE, format!("dl_session={};", state.dl_session))
-        .body(body)
-        .send()
-        .await
-        .map_err(error::ErrorBadGateway)?;
+    let mut resp = None;
+    for _ in 0..state.session_max_attempts {
+        let Some((token_idx, token)) = state.session_pool.next() else {
+            break;
+        };
 
-    if resp.status() == StatusCode::TOO_MANY_REQUESTS {
-        return Err(error::ErrorTooManyRequests(
-                "Too many requests, your IP has been blocked by DeepL temporarily, please don't request it frequently in a short time."
-            ));
+        let (proxy_idx, http_client) = get_client()?;
+
+        let send_result = http_client
+            .post("https://api.deepl.com/jsonrpc")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::COOKIE, format!("dl_session={};", token))
+            .body(body.clone())
+            .send()
+            .await;
+
+        let candidate = match send_result {
+            Ok(candidate) => candidate,
+            Err(e) => {
+                if let Some(idx) = proxy_idx {
+                    report_proxy_failure(idx);
+                }
+                return Err(error::ErrorBadGateway(e));
+            }
+        };
+
+        if candidate.status() == StatusCode::TOO_MANY_REQUESTS {
+            state.session_pool.block(token_idx, state.session_cooldown);
+            continue;
+        }
+
+        if let Some(idx) = proxy_idx {
+            if candidate.status() == StatusCode::BAD_GATEWAY {
+                report_proxy_failure(idx);
+            } else {
+                report_proxy_success(idx);
+            }
+        }
+
+        resp = Some(candidate);
+        break;
     }
 
+    let resp = resp.ok_or_else(|| {
+        error::ErrorTooManyRequests(
+            "Too many requests, all configured dl_session tokens have been temporarily blocked by DeepL.",
+        )
+    })?;
+
     let body = resp
         .error_for_status()
         .map_err(error::ErrorInternalServerError)?
@@ -226,6 +362,17 @@ async fn translate(
         .flatten()
         .unwrap_or_default();
 
+    if let Some(cache) = get_cache() {
+        cache.insert(
+            cache_key,
+            req.text.clone(),
+            source_lang.clone(),
+            target_lang.clone(),
+            data.to_string(),
+            alternatives.clone(),
+        );
+    }
+
     let response = json!({
         "code": StatusCode::OK.as_u16(),
         "id": id,
@@ -287,34 +434,337 @@ pub fn get_timestamp(i_count: usize) -> actix_web::Result<u128> {
     }
 }
 
+/// A pool of `dl_session` tokens, round-robined like [`Client`]'s proxy pool, with
+/// per-token cooldowns so a token DeepL has rate-limited doesn't take the whole
+/// server down while it's blocked.
+struct SessionPool {
+    cursor: AtomicU32,
+    tokens: Vec<(String, AtomicU64)>,
+}
+
+impl SessionPool {
+    fn new(tokens: Vec<String>) -> Self {
+        Self {
+            cursor: AtomicU32::new(0),
+            tokens: tokens
+                .into_iter()
+                .map(|token| (token, AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    // Round-robin, skipping tokens whose cooldown hasn't elapsed yet
+    fn next(&self) -> Option<(usize, &str)> {
+        let len = self.tokens.len() as u32;
+        if len == 0 {
+            return None;
+        }
+
+        let now = now_epoch_ms();
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed) % len;
+        (0..len)
+            .map(|offset| ((start + offset) % len) as usize)
+            .find(|&idx| self.tokens[idx].1.load(Ordering::Relaxed) <= now)
+            .map(|idx| (idx, self.tokens[idx].0.as_str()))
+    }
+
+    fn block(&self, idx: usize, cooldown: Duration) {
+        let until = now_epoch_ms().saturating_add(cooldown.as_millis() as u64);
+        self.tokens[idx].1.store(until, Ordering::Relaxed);
+    }
+}
+
+fn now_epoch_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+static CACHE: OnceLock<Cache> = OnceLock::new();
+
+/// A cached translation result, keyed on `(text, source_lang, target_lang)`. The original
+/// fields are kept alongside the hash so a lookup can verify a genuine match instead of
+/// trusting the (unkeyed, crackable) `DefaultHasher` output alone — a hash collision must
+/// never result in one request's cached translation being served back for another's text.
+struct CacheEntry {
+    text: String,
+    source_lang: String,
+    target_lang: String,
+    data: String,
+    alternatives: Vec<String>,
+    inserted_at: u64,
+}
+
+/// An LRU+TTL cache in front of `translate`'s upstream call, so repeated requests for
+/// the same text don't risk tripping DeepL's rate limiting again.
+struct Cache {
+    ttl: Duration,
+    capacity: usize,
+    inner: std::sync::Mutex<CacheInner>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    entries: std::collections::HashMap<u64, CacheEntry>,
+    // Most-recently-used key is at the back
+    order: std::collections::VecDeque<u64>,
+}
+
+impl Cache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            inner: std::sync::Mutex::new(CacheInner::default()),
+        }
+    }
+
+    fn key(text: &str, source_lang: &str, target_lang: &str) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        text.hash(&mut hasher);
+        source_lang.hash(&mut hasher);
+        target_lang.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get(
+        &self,
+        key: u64,
+        text: &str,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Option<CacheEntry> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let entry = inner.entries.get(&key)?;
+        if now_epoch_ms().saturating_sub(entry.inserted_at) > self.ttl.as_millis() as u64 {
+            inner.entries.remove(&key);
+            inner.order.retain(|k| *k != key);
+            return None;
+        }
+
+        // Guard against a `DefaultHasher` collision serving the wrong cached text back.
+        if entry.text != text || entry.source_lang != source_lang || entry.target_lang != target_lang {
+            return None;
+        }
+
+        inner.order.retain(|k| *k != key);
+        inner.order.push_back(key);
+
+        inner.entries.get(&key).map(|e| CacheEntry {
+            text: e.text.clone(),
+            source_lang: e.source_lang.clone(),
+            target_lang: e.target_lang.clone(),
+            data: e.data.clone(),
+            alternatives: e.alternatives.clone(),
+            inserted_at: e.inserted_at,
+        })
+    }
+
+    fn insert(
+        &self,
+        key: u64,
+        text: String,
+        source_lang: String,
+        target_lang: String,
+        data: String,
+        alternatives: Vec<String>,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if !inner.entries.contains_key(&key) {
+            while inner.entries.len() >= self.capacity {
+                let Some(oldest) = inner.order.pop_front() else {
+                    break;
+                };
+                inner.entries.remove(&oldest);
+            }
+        } else {
+            inner.order.retain(|k| *k != key);
+        }
+
+        inner.entries.insert(
+            key,
+            CacheEntry {
+                text,
+                source_lang,
+                target_lang,
+                data,
+                alternatives,
+                inserted_at: now_epoch_ms(),
+            },
+        );
+        inner.order.push_back(key);
+    }
+}
+
+fn get_cache() -> Option<&'static Cache> {
+    CACHE.get()
+}
+
 static CLIENT: OnceLock<Client> = OnceLock::new();
 
-fn get_client() -> actix_web::Result<reqwest::Client> {
+/// Lightweight liveness probe target used by the background health-check task.
+const PROXY_PROBE_URL: &str = "https://api.deepl.com/";
+/// Consecutive failures (either from a probe or from `translate` itself) before a
+/// pool entry is taken out of rotation.
+const PROXY_FAILURE_THRESHOLD: u32 = 3;
+/// How often the background task re-probes every pool entry.
+const PROXY_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Background task that periodically re-probes every entry in the client pool, evicting
+/// ones that stay down and reinstating ones that recover.
+async fn proxy_health_check_loop() {
+    let mut ticker = actix_web::rt::time::interval(PROXY_PROBE_INTERVAL);
+    loop {
+        ticker.tick().await;
+        if let Some(client) = CLIENT.get() {
+            client.health_check().await;
+        }
+    }
+}
+
+fn get_client() -> actix_web::Result<(Option<usize>, reqwest::Client)> {
     Ok(CLIENT
         .get()
         .ok_or_else(|| error::ErrorInternalServerError("Failed to get the client"))?
         .next())
 }
 
-struct Client(AtomicU32, Vec<reqwest::Client>);
+fn report_proxy_success(idx: usize) {
+    if let Some(client) = CLIENT.get() {
+        client.report_success(idx);
+    }
+}
+
+fn report_proxy_failure(idx: usize) {
+    if let Some(client) = CLIENT.get() {
+        client.report_failure(idx);
+    }
+}
+
+/// One entry in the client pool, with the liveness state [`Client::next`] and the
+/// background health task use to skip/evict/reinstate it.
+struct PoolEntry {
+    client: reqwest::Client,
+    healthy: std::sync::atomic::AtomicBool,
+    failure_count: AtomicU32,
+    next_probe_at: AtomicU64,
+}
+
+impl PoolEntry {
+    fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            healthy: std::sync::atomic::AtomicBool::new(true),
+            failure_count: AtomicU32::new(0),
+            next_probe_at: AtomicU64::new(0),
+        }
+    }
+}
+
+struct Client {
+    cursor: AtomicU32,
+    pool: Vec<PoolEntry>,
+    // Direct (no-proxy) connection, used as a last resort when every proxy is unhealthy.
+    direct: Option<reqwest::Client>,
+}
 
 impl Client {
-    fn new(proxies: Option<Vec<String>>) -> Result<Self> {
-        let mut clients = Vec::new();
+    fn new(
+        proxies: Option<Vec<String>>,
+        dns_resolver: Option<DnsResolverConfig>,
+        client_identities: Vec<Option<reqwest::Identity>>,
+    ) -> Result<Self> {
+        let resolver = dns_resolver
+            .map(CustomResolver::new)
+            .transpose()?
+            .map(|r| Arc::new(r) as Arc<dyn Resolve>);
 
-        if let Some(proxies) = proxies {
-            for proxy in proxies {
-                let client = Self::build_client(Some(proxy))?;
-                clients.push(client);
+        // Used for the direct fallback client too, so falling back doesn't silently stop
+        // presenting a client certificate to an mTLS-gated egress.
+        let direct_identity = client_identities.first().cloned().flatten();
+        let mut identities = client_identities.into_iter();
+        let mut pool = Vec::new();
+
+        let direct = match proxies {
+            Some(proxies) => {
+                for proxy in proxies {
+                    let identity = identities.next().flatten();
+                    let client = Self::build_client(Some(proxy), resolver.clone(), identity)?;
+                    pool.push(PoolEntry::new(client));
+                }
+                Some(Self::build_client(None, resolver.clone(), direct_identity)?)
+            }
+            None => {
+                let identity = identities.next().flatten();
+                let client = Self::build_client(None, resolver.clone(), identity)?;
+                pool.push(PoolEntry::new(client));
+                None
+            }
+        };
+
+        Ok(Self {
+            cursor: AtomicU32::new(0),
+            pool,
+            direct,
+        })
+    }
+
+    fn report_success(&self, idx: usize) {
+        let Some(entry) = self.pool.get(idx) else {
+            return;
+        };
+        entry.failure_count.store(0, Ordering::Relaxed);
+        entry.healthy.store(true, Ordering::Relaxed);
+    }
+
+    fn report_failure(&self, idx: usize) {
+        let Some(entry) = self.pool.get(idx) else {
+            return;
+        };
+        let failures = entry.failure_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= PROXY_FAILURE_THRESHOLD {
+            entry.healthy.store(false, Ordering::Relaxed);
+            let backoff = PROXY_PROBE_INTERVAL.as_millis() as u64 * failures.min(6) as u64;
+            entry
+                .next_probe_at
+                .store(now_epoch_ms() + backoff, Ordering::Relaxed);
+        }
+    }
+
+    /// Probe every pool entry through its own client and update its health state.
+    /// Spawned as a background task from [`Serve::run`].
+    async fn health_check(&self) {
+        for (idx, entry) in self.pool.iter().enumerate() {
+            let is_healthy = entry
+                .client
+                .head(PROXY_PROBE_URL)
+                .send()
+                .await
+                .map(|resp| resp.status().is_success() || resp.status().is_redirection())
+                .unwrap_or(false);
+
+            if is_healthy {
+                self.report_success(idx);
+            } else {
+                self.report_failure(idx);
             }
-        } else {
-            let client = Self::build_client(None)?;
-            clients.push(client);
         }
-        Ok(Self(AtomicU32::new(0), clients))
     }
 
-    fn build_client(proxy: Option<String>) -> Result<reqwest::Client> {
+    fn build_client(
+        proxy: Option<String>,
+        dns_resolver: Option<Arc<dyn Resolve>>,
+        identity: Option<reqwest::Identity>,
+    ) -> Result<reqwest::Client> {
         let mut builder = reqwest::Client::builder()
             .default_headers((|| {
                 let mut headers = header::HeaderMap::new();
@@ -347,30 +797,347 @@ impl Client {
             builder = builder.proxy(reqwest::Proxy::all(&proxy)?);
         }
 
+        if let Some(dns_resolver) = dns_resolver {
+            builder = builder.dns_resolver(dns_resolver);
+        }
+
+        if let Some(identity) = identity {
+            builder = builder.identity(identity);
+        }
+
         builder.build().map_err(Into::into)
     }
-    // Round-robin client
-    fn next(&self) -> reqwest::Client {
-        let pool = &self.1;
-        if self.1.len() == 1 {
-            self.1[0].clone()
-        } else {
-            let len = self.1.len() as u32;
-            let mut old = self.0.load(Ordering::Relaxed);
-            let mut new;
-            loop {
-                new = (old + 1) % len;
-                match self
-                    .0
-                    .compare_exchange_weak(old, new, Ordering::SeqCst, Ordering::Relaxed)
-                {
-                    Ok(_) => break,
-                    Err(x) => old = x,
-                }
+    // Round-robin across healthy pool entries, skipping ones that are down until their
+    // `next_probe_at` has passed (an entry that is due is handed out again so the request
+    // itself re-probes it); falls back to a direct connection (or, lacking one, the next
+    // entry anyway) only once every entry is unhealthy and none is due.
+    fn next(&self) -> (Option<usize>, reqwest::Client) {
+        let len = self.pool.len() as u32;
+        let now = now_epoch_ms();
+        let mut old = self.cursor.load(Ordering::Relaxed);
+        let mut new;
+        loop {
+            new = (old + 1) % len;
+            match self
+                .cursor
+                .compare_exchange_weak(old, new, Ordering::SeqCst, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(x) => old = x,
+            }
+        }
+
+        for offset in 0..len {
+            let idx = ((new + offset) % len) as usize;
+            let entry = &self.pool[idx];
+            if entry.healthy.load(Ordering::Relaxed)
+                || entry.next_probe_at.load(Ordering::Relaxed) <= now
+            {
+                return (Some(idx), entry.client.clone());
             }
-            pool[new as usize].clone()
         }
+
+        // Every entry is unhealthy: fall back to a direct connection if we have one,
+        // otherwise best-effort retry through the next entry in rotation.
+        match &self.direct {
+            Some(direct) => (None, direct.clone()),
+            None => (
+                Some(new as usize),
+                self.pool[new as usize].client.clone(),
+            ),
+        }
+    }
+}
+
+/// Operator-selected DNS backend for [`CustomResolver`].
+enum DnsResolverConfig {
+    /// Forward lookups to a fixed UDP nameserver instead of the system stub resolver.
+    Nameserver(std::net::SocketAddr),
+    /// Resolve names via DNS-over-HTTPS (RFC 8484) against the given `dns-query` endpoint.
+    DnsOverHttps(String),
+}
+
+/// A `reqwest::dns::Resolve` implementation that lets operators route DeepL's hostname
+/// through a custom nameserver or DoH endpoint (e.g. to evade DNS-level interference, or
+/// to pin egress per proxy) instead of the platform's default resolver.
+struct CustomResolver {
+    config: DnsResolverConfig,
+    http: reqwest::Client,
+    cache: Arc<std::sync::Mutex<std::collections::HashMap<String, (Vec<std::net::SocketAddr>, std::time::Instant)>>>,
+}
+
+impl CustomResolver {
+    fn new(config: DnsResolverConfig) -> Result<Self> {
+        Ok(Self {
+            config,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(CONNECTION_TIMEOUT as u64))
+                .build()?,
+            cache: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+        })
+    }
+
+    fn cached(
+        cache: &std::sync::Mutex<std::collections::HashMap<String, (Vec<std::net::SocketAddr>, std::time::Instant)>>,
+        host: &str,
+    ) -> Option<Vec<std::net::SocketAddr>> {
+        let cache = cache.lock().unwrap();
+        let (addrs, expires_at) = cache.get(host)?;
+        (std::time::Instant::now() < *expires_at).then(|| addrs.clone())
+    }
+
+    fn store(
+        cache: &std::sync::Mutex<std::collections::HashMap<String, (Vec<std::net::SocketAddr>, std::time::Instant)>>,
+        host: String,
+        addrs: Vec<std::net::SocketAddr>,
+        ttl: Duration,
+    ) {
+        let mut cache = cache.lock().unwrap();
+        cache.insert(host, (addrs, std::time::Instant::now() + ttl));
     }
+
+    async fn lookup_via_nameserver(
+        nameserver: std::net::SocketAddr,
+        host: String,
+    ) -> Result<(Vec<std::net::SocketAddr>, Duration)> {
+        let (query, id) = build_dns_query(&host);
+
+        let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(nameserver).await?;
+        socket.send(&query).await?;
+
+        let mut buf = [0u8; 512];
+        let len = tokio::time::timeout(
+            Duration::from_secs(CONNECTION_TIMEOUT as u64),
+            socket.recv(&mut buf),
+        )
+        .await??;
+
+        parse_dns_answer(&buf[..len], id, &host)
+    }
+
+    async fn lookup_via_doh(
+        http: reqwest::Client,
+        endpoint: String,
+        host: String,
+    ) -> Result<(Vec<std::net::SocketAddr>, Duration)> {
+        let (query, id) = build_dns_query(&host);
+
+        let resp = http
+            .post(&endpoint)
+            .header(header::CONTENT_TYPE, "application/dns-message")
+            .header(header::ACCEPT, "application/dns-message")
+            .body(query)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let bytes = resp.bytes().await?;
+        parse_dns_answer(&bytes, id, &host)
+    }
+}
+
+impl Resolve for CustomResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let cache = self.cache.clone();
+
+        if let Some(addrs) = Self::cached(&cache, &host) {
+            return Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) });
+        }
+
+        match &self.config {
+            DnsResolverConfig::Nameserver(nameserver) => {
+                let nameserver = *nameserver;
+                Box::pin(async move {
+                    let (addrs, ttl) = Self::lookup_via_nameserver(nameserver, host.clone())
+                        .await
+                        .map_err(|e| -> reqwest::dns::BoxError { e.into() })?;
+                    Self::store(&cache, host, addrs.clone(), ttl);
+                    Ok(Box::new(addrs.into_iter()) as Addrs)
+                })
+            }
+            DnsResolverConfig::DnsOverHttps(endpoint) => {
+                let http = self.http.clone();
+                let endpoint = endpoint.clone();
+                Box::pin(async move {
+                    let (addrs, ttl) = Self::lookup_via_doh(http, endpoint, host.clone())
+                        .await
+                        .map_err(|e| -> reqwest::dns::BoxError { e.into() })?;
+                    Self::store(&cache, host, addrs.clone(), ttl);
+                    Ok(Box::new(addrs.into_iter()) as Addrs)
+                })
+            }
+        }
+    }
+}
+
+/// Build a minimal RFC 1035 wire-format A-record query (also valid as the body of an
+/// RFC 8484 DoH request), tagged with a random 16-bit ID so the response can be matched
+/// back to this query instead of trusting whatever answer shows up.
+fn build_dns_query(host: &str) -> (Vec<u8>, u16) {
+    let id: u16 = rand::thread_rng().gen();
+
+    let mut packet = Vec::with_capacity(16 + host.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0x01, 0x00]); // flags: standard query, recursion desired
+    packet.extend_from_slice(&[0x00, 0x01]); // QDCOUNT = 1
+    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // AN/NS/ARCOUNT = 0
+
+    for label in host.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0x00);
+    packet.extend_from_slice(&[0x00, 0x01]); // QTYPE = A
+    packet.extend_from_slice(&[0x00, 0x01]); // QCLASS = IN
+
+    (packet, id)
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16> {
+    let end = pos.checked_add(2).filter(|&e| e <= bytes.len());
+    let chunk = end
+        .and_then(|e| bytes.get(pos..e))
+        .ok_or_else(|| anyhow::anyhow!("truncated DNS response"))?;
+    Ok(u16::from_be_bytes([chunk[0], chunk[1]]))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+    let end = pos.checked_add(4).filter(|&e| e <= bytes.len());
+    let chunk = end
+        .and_then(|e| bytes.get(pos..e))
+        .ok_or_else(|| anyhow::anyhow!("truncated DNS response"))?;
+    Ok(u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+}
+
+/// Parse the answer section of a DNS response, returning the A records found and the
+/// lowest TTL among them (used to bound how long we cache the lookup). Validates the
+/// response actually answers `expected_id`/`expected_host` before trusting it, since
+/// this may be read off a connected-but-unauthenticated UDP socket.
+fn parse_dns_answer(
+    bytes: &[u8],
+    expected_id: u16,
+    expected_host: &str,
+) -> Result<(Vec<std::net::SocketAddr>, Duration)> {
+    if bytes.len() < 12 {
+        return Err(anyhow::anyhow!("DNS response too short"));
+    }
+
+    if u16::from_be_bytes([bytes[0], bytes[1]]) != expected_id {
+        return Err(anyhow::anyhow!(
+            "DNS response id did not match the query (possible spoofed/stray reply)"
+        ));
+    }
+
+    let flags = u16::from_be_bytes([bytes[2], bytes[3]]);
+    if flags & 0x8000 == 0 {
+        return Err(anyhow::anyhow!("DNS reply is not marked as a response"));
+    }
+    let rcode = flags & 0x000f;
+    if rcode != 0 {
+        return Err(anyhow::anyhow!("DNS server returned error code {rcode}"));
+    }
+
+    let qdcount = u16::from_be_bytes([bytes[4], bytes[5]]) as usize;
+    let ancount = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+    let expected_host = expected_host.trim_end_matches('.');
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        let (name, next_pos) = read_dns_name(bytes, pos)?;
+        if !name.eq_ignore_ascii_case(expected_host) {
+            return Err(anyhow::anyhow!(
+                "DNS response question does not match the query"
+            ));
+        }
+        pos = next_pos
+            .checked_add(4) // QTYPE + QCLASS
+            .filter(|&p| p <= bytes.len())
+            .ok_or_else(|| anyhow::anyhow!("truncated DNS response"))?;
+    }
+
+    let mut addrs = Vec::new();
+    let mut min_ttl = u32::MAX;
+    for _ in 0..ancount {
+        pos = skip_dns_name(bytes, pos)?;
+        let rtype = read_u16(bytes, pos)?;
+        let ttl = read_u32(bytes, pos + 4)?;
+        let rdlength = read_u16(bytes, pos + 8)? as usize;
+        pos = pos
+            .checked_add(10)
+            .filter(|&p| p <= bytes.len())
+            .ok_or_else(|| anyhow::anyhow!("truncated DNS response"))?;
+
+        let rdata_end = pos
+            .checked_add(rdlength)
+            .filter(|&e| e <= bytes.len())
+            .ok_or_else(|| anyhow::anyhow!("truncated DNS response"))?;
+
+        if rtype == 1 && rdlength == 4 {
+            let rdata = &bytes[pos..rdata_end];
+            addrs.push(std::net::SocketAddr::from((
+                [rdata[0], rdata[1], rdata[2], rdata[3]],
+                0,
+            )));
+            min_ttl = min_ttl.min(ttl);
+        }
+        pos = rdata_end;
+    }
+
+    if addrs.is_empty() {
+        return Err(anyhow::anyhow!("DNS response contained no A records"));
+    }
+
+    Ok((addrs, Duration::from_secs(min_ttl.max(1) as u64)))
+}
+
+/// Decode a (possibly compressed) DNS name starting at `pos`, returning the dotted name
+/// and the position immediately after it (after following any compression pointer).
+fn read_dns_name(bytes: &[u8], start: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = start;
+    let mut after_pointer = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *bytes
+            .get(pos)
+            .ok_or_else(|| anyhow::anyhow!("truncated DNS name"))? as usize;
+
+        if len == 0 {
+            pos += 1;
+            break;
+        }
+
+        if len & 0xc0 == 0xc0 {
+            jumps += 1;
+            if jumps > 5 {
+                return Err(anyhow::anyhow!("DNS name compression loop"));
+            }
+            let lo = *bytes
+                .get(pos + 1)
+                .ok_or_else(|| anyhow::anyhow!("truncated DNS name"))?;
+            if after_pointer.is_none() {
+                after_pointer = Some(pos + 2);
+            }
+            pos = ((len & 0x3f) << 8) | lo as usize;
+            continue;
+        }
+
+        let label_end = pos + 1 + len;
+        let label = bytes
+            .get(pos + 1..label_end)
+            .ok_or_else(|| anyhow::anyhow!("truncated DNS name"))?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos = label_end;
+    }
+
+    Ok((labels.join("."), after_pointer.unwrap_or(pos)))
+}
+
+fn skip_dns_name(bytes: &[u8], pos: usize) -> Result<usize> {
+    read_dns_name(bytes, pos).map(|(_, next)| next)
 }
 
 use serde::{Deserialize, Serialize};